@@ -76,6 +76,9 @@ extern crate core as std;
 
 use std::{mem, ptr};
 
+#[cfg(feature = "std")]
+pub mod scoped;
+
 struct CatchUnwind<F: FnOnce()>(mem::ManuallyDrop<F>);
 impl<F: FnOnce()> Drop for CatchUnwind<F> {
 	#[inline(always)]
@@ -287,6 +290,238 @@ pub unsafe fn replace_with_or_abort_unchecked<T, F: FnOnce(T) -> T>(dest: &mut T
 	ptr::write(dest, f(ptr::read(dest)));
 }
 
+/// Temporarily takes ownership of a value at a mutable location, and replace it with a new value
+/// based on the old one, letting the closure additionally hand back an arbitrary value `R`.
+///
+/// This is [`replace_with()`] with a mapping closure of type `FnOnce(T) -> (T, R)` rather than
+/// `FnOnce(T) -> T`, for when something needs to be computed from the owned value `t` as it's
+/// being replaced, without smuggling it out through captured `&mut` state.
+///
+/// # An important note
+///
+/// On panic (or to be more precise, unwinding) of the closure `f`, `default` will be called to
+/// provide a replacement value. `default` should not panic – doing so will constitute a double
+/// panic and will most likely abort the process.
+///
+/// # Example
+///
+/// ```
+/// # use replace_with::*;
+/// enum States {
+/// 	A(String),
+/// 	B(String),
+/// }
+///
+/// impl States {
+/// 	fn poll(&mut self) -> usize {
+/// 		replace_with_and_return(
+/// 			self,
+/// 			|| States::A(String::new()),
+/// 			|self_| match self_ {
+/// 				States::A(a) => {
+/// 					let len = a.len();
+/// 					(States::B(a), len)
+/// 				}
+/// 				States::B(a) => {
+/// 					let len = a.len();
+/// 					(States::A(a), len)
+/// 				}
+/// 			},
+/// 		)
+/// 	}
+/// }
+/// ```
+#[inline]
+pub fn replace_with_and_return<T, R, D: FnOnce() -> T, F: FnOnce(T) -> (T, R)>(
+	dest: &mut T, default: D, f: F,
+) -> R {
+	unsafe {
+		let t = ptr::read(dest);
+		let (t, ret) = catch_unwind(move || f(t), || ptr::write(dest, default()));
+		ptr::write(dest, t);
+		ret
+	}
+}
+
+/// Temporarily takes ownership of a value at a mutable location, and replace it with a new value
+/// based on the old one, letting the closure additionally hand back an arbitrary value `R`.
+/// Replaces with [`Default::default()`] on panic.
+///
+/// Equivalent to `replace_with_and_return(dest, T::default, f)`.
+///
+/// # An important note
+///
+/// On panic (or to be more precise, unwinding) of the closure `f`, `T::default()` will be called
+/// to provide a replacement value. `T::default()` should not panic – doing so will constitute a
+/// double panic and will most likely abort the process.
+#[inline]
+pub fn replace_with_and_return_or_default<T: Default, R, F: FnOnce(T) -> (T, R)>(
+	dest: &mut T, f: F,
+) -> R {
+	replace_with_and_return(dest, T::default, f)
+}
+
+/// Temporarily takes ownership of a value at a mutable location, and replace it with a new value
+/// based on the old one, letting the closure additionally hand back an arbitrary value `R`. Aborts
+/// on panic.
+///
+/// Equivalent to `replace_with_and_return(dest, || process::abort(), f)`.
+///
+/// # An important note
+///
+/// On panic (or to be more precise, unwinding) of the closure `f`, the process will **abort** to
+/// avoid returning control while `dest` is in a potentially invalid state.
+///
+/// If this behaviour is undesirable, use [replace_with_and_return] or
+/// [replace_with_and_return_or_default].
+#[inline]
+#[cfg(feature = "std")]
+pub fn replace_with_and_return_or_abort<T, R, F: FnOnce(T) -> (T, R)>(dest: &mut T, f: F) -> R {
+	replace_with_and_return(dest, || std::process::abort(), f)
+}
+
+#[inline]
+#[cfg(all(not(feature = "std"), feature = "nightly"))]
+pub fn replace_with_and_return_or_abort<T, R, F: FnOnce(T) -> (T, R)>(dest: &mut T, f: F) -> R {
+	replace_with_and_return(dest, || unsafe { std::intrinsics::abort() }, f)
+}
+
+/// Temporarily takes ownership of a value at a mutable location, and replace it with a new value
+/// based on the old one, where the mapping closure can fail.
+///
+/// We move out of the reference temporarily, to apply a closure `f`, which on success returns a
+/// new value to be placed at the original value's location, or on failure an error `E`, in which
+/// case `default` is called to provide a replacement so that `dest` is left in a valid state.
+///
+/// # An important note
+///
+/// On panic (or to be more precise, unwinding) of the closure `f`, `default` will be called to
+/// provide a replacement value. `default` should not panic – doing so will constitute a double
+/// panic and will most likely abort the process.
+///
+/// # Example
+///
+/// ```
+/// # use replace_with::*;
+/// enum States {
+/// 	A(String),
+/// 	B(String),
+/// }
+///
+/// impl States {
+/// 	fn poll(&mut self) -> Result<(), std::num::ParseIntError> {
+/// 		try_replace_with(
+/// 			self,
+/// 			|| States::A(String::new()),
+/// 			|self_| match self_ {
+/// 				States::A(a) => {
+/// 					let _: i32 = a.parse()?;
+/// 					Ok(States::B(a))
+/// 				}
+/// 				States::B(a) => Ok(States::A(a)),
+/// 			},
+/// 		)
+/// 	}
+/// }
+/// ```
+#[inline]
+pub fn try_replace_with<T, E, D: FnOnce() -> T, F: FnOnce(T) -> Result<T, E>>(
+	dest: &mut T, default: D, f: F,
+) -> Result<(), E> {
+	unsafe {
+		let t = ptr::read(dest);
+		let guard = CatchUnwind(mem::ManuallyDrop::new(|| ptr::write(dest, default())));
+		let result = f(t);
+		let recover = ptr::read(&*guard.0);
+		mem::forget(guard);
+		match result {
+			Ok(t) => {
+				ptr::write(dest, t);
+				Ok(())
+			}
+			Err(e) => {
+				recover();
+				Err(e)
+			}
+		}
+	}
+}
+
+/// Temporarily takes ownership of a value at a mutable location, and replace it with a new value
+/// based on the old one, where the mapping closure can fail. Replaces with [`Default::default()`]
+/// on panic or on failure.
+///
+/// Equivalent to `try_replace_with(dest, T::default, f)`.
+///
+/// # An important note
+///
+/// On panic (or to be more precise, unwinding) of the closure `f`, `T::default()` will be called
+/// to provide a replacement value. `T::default()` should not panic – doing so will constitute a
+/// double panic and will most likely abort the process.
+#[inline]
+pub fn try_replace_with_or_default<T: Default, E, F: FnOnce(T) -> Result<T, E>>(
+	dest: &mut T, f: F,
+) -> Result<(), E> {
+	try_replace_with(dest, T::default, f)
+}
+
+/// Temporarily takes ownership of a value at a mutable location, and replace it with a new value
+/// based on the old one, catching a panic of the mapping closure rather than letting it unwind or
+/// abort.
+///
+/// Unlike [`replace_with()`], which writes `default()` into `dest` and then lets the panic continue
+/// to unwind, this uses [`std::panic::catch_unwind()`] to catch the panic and hand the payload back
+/// to the caller, for use at FFI boundaries and plugin hosts where aborting the whole process on a
+/// panic is unacceptable.
+///
+/// # An important note
+///
+/// This incurs the cost of [`std::panic::catch_unwind()`], the optimisation barrier this crate
+/// otherwise avoids – see the [crate-level documentation](crate). Prefer [`replace_with()`] or
+/// [`replace_with_or_abort()`] unless you specifically need the panic payload.
+///
+/// # Example
+///
+/// ```
+/// # use replace_with::*;
+/// enum States {
+/// 	A(String),
+/// 	B(String),
+/// }
+///
+/// impl States {
+/// 	fn poll(&mut self) -> Result<(), Box<dyn std::any::Any + Send>> {
+/// 		replace_with_or_catch(
+/// 			self,
+/// 			|| States::A(String::new()),
+/// 			|self_| match self_ {
+/// 				States::A(a) => States::B(a),
+/// 				States::B(a) => States::A(a),
+/// 			},
+/// 		)
+/// 	}
+/// }
+/// ```
+#[inline]
+#[cfg(feature = "std")]
+pub fn replace_with_or_catch<T, D: FnOnce() -> T, F: FnOnce(T) -> T>(
+	dest: &mut T, default: D, f: F,
+) -> Result<(), Box<dyn std::any::Any + Send>> {
+	unsafe {
+		let t = ptr::read(dest);
+		match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| f(t))) {
+			Ok(t) => {
+				ptr::write(dest, t);
+				Ok(())
+			}
+			Err(payload) => {
+				ptr::write(dest, default());
+				Err(payload)
+			}
+		}
+	}
+}
+
 #[cfg(test)]
 mod test {
 	// These functions copied from https://github.com/Sgeo/take_mut/blob/1bd70d842c6febcd16ec1fe3a954a84032b89f52/src/lib.rs#L102-L147
@@ -387,4 +622,75 @@ mod test {
 		assert!(res.is_err());
 		assert_eq!(&quax, &Foo::C);
 	}
+
+	#[test]
+	fn it_works_and_return() {
+		#[derive(PartialEq, Eq, Debug)]
+		enum Foo {
+			A,
+			B,
+		}
+		let mut quax = Foo::A;
+		let len = replace_with_and_return(
+			&mut quax,
+			|| Foo::A,
+			|f| match f {
+				Foo::A => (Foo::B, 1),
+				Foo::B => (Foo::A, 2),
+			},
+		);
+		assert_eq!(&quax, &Foo::B);
+		assert_eq!(len, 1);
+	}
+
+	#[test]
+	fn it_works_try() {
+		#[derive(PartialEq, Eq, Debug)]
+		enum Foo {
+			A,
+			B,
+			C,
+		}
+		let mut quax = Foo::A;
+
+		let res = try_replace_with(&mut quax, || Foo::C, |f| match f {
+			Foo::A => Ok(Foo::B),
+			_ => Err("bad state"),
+		});
+		assert!(res.is_ok());
+		assert_eq!(&quax, &Foo::B);
+
+		let res = try_replace_with(&mut quax, || Foo::C, |f| match f {
+			Foo::A => Ok(Foo::B),
+			_ => Err("bad state"),
+		});
+		assert_eq!(res, Err("bad state"));
+		assert_eq!(&quax, &Foo::C);
+	}
+
+	#[cfg(feature = "std")]
+	#[test]
+	fn it_works_catch() {
+		#[derive(PartialEq, Eq, Debug)]
+		enum Foo {
+			A,
+			B,
+			C,
+		}
+		let mut quax = Foo::A;
+
+		let res = replace_with_or_catch(&mut quax, || Foo::C, |f| match f {
+			Foo::A => Foo::B,
+			_ => panic!("panic"),
+		});
+		assert!(res.is_ok());
+		assert_eq!(&quax, &Foo::B);
+
+		let res = replace_with_or_catch(&mut quax, || Foo::C, |f| match f {
+			Foo::A => Foo::B,
+			_ => panic!("panic"),
+		});
+		assert!(res.is_err());
+		assert_eq!(&quax, &Foo::C);
+	}
 }
@@ -0,0 +1,207 @@
+//! Take ownership of values at several `&mut T` locations at once, within a `scope`.
+//!
+//! [`replace_with()`](crate::replace_with) and friends only let you move a single `&mut T` out at
+//! a time, as the replacement must be ready before the function returns. Sometimes, however, the
+//! replacements for several locations can only be computed once all of them have been moved out –
+//! for example, swapping fields across two nodes. [`scope()`] and [`Scope::take()`] allow exactly
+//! that, at the cost of a runtime check (and, by default, abort) that every opened [`Hole`] was
+//! filled before the scope ends.
+
+use std::{cell::RefCell, process, ptr};
+
+struct HoleState<'s> {
+	filled: bool,
+	recover: Option<Box<dyn FnOnce() + 's>>,
+}
+
+/// Handle passed to the closure given to [`scope()`], used to [`take`](Scope::take) values out of
+/// `&mut T` locations for the duration of the scope.
+pub struct Scope<'s> {
+	holes: RefCell<Vec<HoleState<'s>>>,
+}
+
+impl<'s> Scope<'s> {
+	/// Moves the value out of `dest`, returning it along with a [`Hole`] that must be
+	/// [`fill`](Hole::fill)ed with a replacement before this scope returns.
+	///
+	/// `dest` stays mutably borrowed for as long as the returned [`Hole`] is open, so it's
+	/// impossible to read or write the original location, even through its original binding, until
+	/// the [`Hole`] is [`fill`](Hole::fill)ed.
+	pub fn take<'h, T: 'h>(&'h self, dest: &'h mut T) -> (T, Hole<'h, 's, T>) {
+		let t = unsafe { ptr::read(dest) };
+		let index = {
+			let mut holes = self.holes.borrow_mut();
+			holes.push(HoleState {
+				filled: false,
+				recover: None,
+			});
+			holes.len() - 1
+		};
+		(
+			t,
+			Hole {
+				scope: self,
+				index,
+				dest,
+			},
+		)
+	}
+}
+
+impl<'s> Drop for Scope<'s> {
+	fn drop(&mut self) {
+		let mut holes = self.holes.borrow_mut();
+		let unwinding = std::thread::panicking();
+		for hole in holes.iter_mut().filter(|hole| !hole.filled) {
+			match hole.recover.take() {
+				// Unwinding through an open hole with a registered recovery: run it to leave the
+				// original location initialised before the unwind continues past this scope.
+				Some(recover) if unwinding => recover(),
+				// Otherwise the original location would be left uninitialised, so abort rather than
+				// return control (or continue unwinding past it) with invalid state.
+				_ => process::abort(),
+			}
+		}
+	}
+}
+
+/// A still-open hole left by [`Scope::take()`], recording the location a value was moved out of.
+/// Must be [`fill`](Hole::fill)ed with a replacement before the enclosing [`scope()`] returns.
+pub struct Hole<'h, 's, T: 's> {
+	scope: &'h Scope<'s>,
+	index: usize,
+	dest: *mut T,
+}
+
+impl<'h, 's, T: 's> Hole<'h, 's, T> {
+	/// Registers `recover` to be called in place of [`fill`](Hole::fill), to reinitialise the
+	/// original location, if this [`Hole`] is still open when the enclosing [`scope()`] unwinds.
+	///
+	/// This is a safety net for unwinding only – if [`scope()`] returns normally with this [`Hole`]
+	/// still unfilled, the process aborts regardless of whether `recover` was registered.
+	pub fn on_unwind(&self, recover: impl FnOnce() -> T + 's) {
+		let dest = self.dest;
+		self.scope.holes.borrow_mut()[self.index].recover =
+			Some(Box::new(move || unsafe { ptr::write(dest, recover()) }));
+	}
+
+	/// Writes `value` into the location this [`Hole`] was taken from, closing it.
+	pub fn fill(self, value: T) {
+		unsafe { ptr::write(self.dest, value) };
+		self.scope.holes.borrow_mut()[self.index].filled = true;
+	}
+}
+
+/// Opens a scope within which values can be [`take`](Scope::take)n out of several `&mut T`
+/// locations at once, to be [`fill`](Hole::fill)ed back in once their replacements are ready.
+///
+/// # An important note
+///
+/// Every [`Hole`] opened by [`Scope::take()`] must be [`fill`](Hole::fill)ed before `f` returns –
+/// if any are left open when `f` returns normally, the process will **abort**, matching this
+/// crate's abort-on-invalid-state philosophy, since the original location would otherwise be left
+/// uninitialised. If `f` instead unwinds with a [`Hole`] still open, the process also aborts
+/// unless a recovery closure was registered for it via [`Hole::on_unwind()`], in which case that
+/// closure is run to reinitialise the original location before the unwind continues.
+///
+/// # Example
+///
+/// ```
+/// # use replace_with::scoped::scope;
+/// struct Node {
+/// 	value: String,
+/// }
+///
+/// let mut a = Node { value: "a".to_owned() };
+/// let mut b = Node { value: "b".to_owned() };
+///
+/// scope(|s| {
+/// 	let (a_value, a_hole) = s.take(&mut a.value);
+/// 	let (b_value, b_hole) = s.take(&mut b.value);
+/// 	a_hole.fill(b_value);
+/// 	b_hole.fill(a_value);
+/// });
+///
+/// assert_eq!(a.value, "b");
+/// assert_eq!(b.value, "a");
+/// ```
+///
+/// The original location stays borrowed for as long as its [`Hole`] is open, so it can't be read
+/// until the [`Hole`] has been [`fill`](Hole::fill)ed:
+///
+/// ```compile_fail
+/// # use replace_with::scoped::scope;
+/// let mut a = String::from("a");
+/// scope(|s| {
+/// 	let (value, hole) = s.take(&mut a);
+/// 	println!("{}", a); // error[E0502]: cannot borrow `a` as immutable because it is also borrowed as mutable
+/// 	hole.fill(value);
+/// });
+/// ```
+pub fn scope<'s, R>(f: impl FnOnce(&Scope<'s>) -> R) -> R {
+	let scope = Scope {
+		holes: RefCell::new(Vec::new()),
+	};
+	f(&scope)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn it_works_swap() {
+		struct Node {
+			value: String,
+		}
+
+		let mut a = Node {
+			value: "a".to_owned(),
+		};
+		let mut b = Node {
+			value: "b".to_owned(),
+		};
+
+		scope(|s| {
+			let (a_value, a_hole) = s.take(&mut a.value);
+			let (b_value, b_hole) = s.take(&mut b.value);
+			a_hole.fill(b_value);
+			b_hole.fill(a_value);
+		});
+
+		assert_eq!(a.value, "b");
+		assert_eq!(b.value, "a");
+	}
+
+	#[test]
+	fn it_works_fill() {
+		let mut a = String::from("a");
+
+		scope(|s| {
+			let (value, hole) = s.take(&mut a);
+			assert_eq!(value, "a");
+			hole.fill(String::from("b"));
+		});
+
+		assert_eq!(a, "b");
+	}
+
+	#[test]
+	fn it_works_unwind_recover() {
+		use std::panic;
+
+		let mut a = String::from("a");
+
+		let res = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+			scope(|s| {
+				let (value, hole) = s.take(&mut a);
+				hole.on_unwind(|| String::from("recovered"));
+				drop(value);
+				panic!("panic");
+			});
+		}));
+
+		assert!(res.is_err());
+		assert_eq!(a, "recovered");
+	}
+}